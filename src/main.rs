@@ -1,18 +1,16 @@
 use clap::Parser;
 use memmap2::MmapOptions;
 use rayon::prelude::*;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, Write};
-use std::num::NonZeroU32;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[command(name = "juggl")]
 #[command(about = "Shuffles chunks of a file based on delimiters", long_about = None)]
 struct Args {
-    #[arg(help = "Input file path")]
+    #[arg(help = "Input file path, or - to read from stdin")]
     input: PathBuf,
 
     #[arg(short, long, help = "Delimiter (supports escape sequences like \\x00)")]
@@ -23,6 +21,21 @@ struct Args {
 
     #[arg(short, long, help = "Number of threads for parallel processing (default: number of CPU cores)")]
     threads: Option<usize>,
+
+    #[arg(short = 'n', long, help = "Emit only COUNT randomly chosen chunks instead of the whole file")]
+    count: Option<usize>,
+
+    #[arg(short, long, help = "Write the result to FILE instead of stdout (atomically, via a sibling temp file)")]
+    output: Option<PathBuf>,
+
+    #[arg(long, help = "Never append a trailing delimiter, even if the input ended with one")]
+    no_trailing_delimiter: bool,
+
+    #[arg(long, help = "Remove duplicate chunks (by content) before shuffling, keeping the first occurrence of each")]
+    uniq: bool,
+
+    #[arg(long, help = "With --uniq, print the number of duplicate chunks removed to stderr")]
+    count_duplicates: bool,
 }
 
 fn parse_delimiter(delim: &str) -> Vec<u8> {
@@ -78,54 +91,182 @@ fn parse_delimiter(delim: &str) -> Vec<u8> {
     result
 }
 
-fn count_chunks_parallel(data: &[u8], delimiter: &[u8]) -> usize {
-    if delimiter.is_empty() || data.is_empty() {
-        return 1;
+fn feistel_round_hash(seed: u64, round: u32, half_bits: u32, value: u64) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    round.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish() & ((1u64 << half_bits) - 1)
+}
+
+// Balanced Feistel network permutation over [0, domain), with cycle-walking
+// for non-power-of-two domains. The domain is a u64, so there is no u32 cap.
+struct FeistelPermutation {
+    seed: u64,
+    domain: u64,
+    half_bits: u32,
+    rounds: u32,
+}
+
+impl FeistelPermutation {
+    fn new(seed: u64, domain: u64) -> Self {
+        let half_bits = Self::half_bits_for(domain);
+        FeistelPermutation {
+            seed,
+            domain,
+            half_bits,
+            rounds: 4,
+        }
+    }
+
+    // Smallest b >= 1 such that 2^(2b) >= domain.
+    fn half_bits_for(domain: u64) -> u32 {
+        let mut b = 1u32;
+        while (1u64 << (2 * b)) < domain {
+            b += 1;
+        }
+        b
     }
 
-    let delimiter = Arc::new(delimiter.to_vec());
+    fn feistel_round(&self, value: u64) -> u64 {
+        let mask = (1u64 << self.half_bits) - 1;
+        let mut left = (value >> self.half_bits) & mask;
+        let mut right = value & mask;
+
+        for round in 0..self.rounds {
+            let f = feistel_round_hash(self.seed, round, self.half_bits, right);
+            let new_right = left ^ f;
+            left = right;
+            right = new_right;
+        }
+
+        (left << self.half_bits) | right
+    }
+
+    fn permute(&self, i: u64) -> u64 {
+        let mut v = i;
+        loop {
+            v = self.feistel_round(v);
+            if v < self.domain {
+                return v;
+            }
+        }
+    }
+}
+
+// Builds the chunk index in a single parallel pass: data is split into
+// blocks, each block records every position where the delimiter occurs,
+// then the per-block lists are merged in order.
+fn build_chunk_index(data: &[u8], delimiter: &[u8]) -> Vec<(usize, usize)> {
     let data_len = data.len();
     let delim_len = delimiter.len();
-    
-    if data_len < delim_len {
-        return 1;
+
+    if delim_len == 0 || data_len == 0 || data_len < delim_len {
+        return vec![(0, data_len)];
     }
-    
-    let chunk_count = AtomicUsize::new(1); // Start with 1 for the initial chunk
-    let chunk_size = std::cmp::max(1_000_000, data_len / rayon::current_num_threads());
-    
-    (0..data_len)
+
+    let block_size = std::cmp::max(1_000_000, data_len / rayon::current_num_threads());
+
+    let raw_matches: Vec<usize> = (0..data_len)
         .into_par_iter()
-        .step_by(chunk_size)
-        .for_each(|start| {
-            let end = std::cmp::min(start + chunk_size + delim_len - 1, data_len);
-            let delimiter = delimiter.clone();
-            let mut local_count = 0;
-            
+        .step_by(block_size)
+        .map(|start| {
+            let end = std::cmp::min(start + block_size, data_len);
+            let mut matches = Vec::new();
             let mut i = start;
-            while i <= end.saturating_sub(delim_len) {
-                if &data[i..i + delim_len] == delimiter.as_slice() {
-                    local_count += 1;
-                    i += delim_len;
-                } else {
-                    i += 1;
+
+            while i < end {
+                if i + delim_len <= data_len && &data[i..i + delim_len] == delimiter {
+                    matches.push(i);
                 }
+                i += 1;
             }
-            
-            if local_count > 0 {
-                chunk_count.fetch_add(local_count, Ordering::Relaxed);
+
+            matches
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .flatten()
+        .collect();
+
+    // raw_matches holds every occurrence, overlapping ones included, and is
+    // already sorted; keeping each match that starts at or after the previous
+    // kept match's end gives the same matches as a serial left-to-right scan,
+    // also where a self-overlapping delimiter straddles a block seam.
+    let mut delimiter_starts = Vec::with_capacity(raw_matches.len());
+    let mut next_allowed = 0;
+
+    for delim_start in raw_matches {
+        if delim_start >= next_allowed {
+            delimiter_starts.push(delim_start);
+            next_allowed = delim_start + delim_len;
+        }
+    }
+
+    let mut chunks = Vec::with_capacity(delimiter_starts.len() + 1);
+    let mut chunk_start = 0;
+
+    for delim_start in delimiter_starts {
+        chunks.push((chunk_start, delim_start));
+        chunk_start = delim_start + delim_len;
+    }
+
+    if chunk_start < data_len {
+        chunks.push((chunk_start, data_len));
+    }
+
+    chunks
+}
+
+// Algorithm R reservoir: keeps a uniform random k-subset in one scan.
+struct Reservoir {
+    k: usize,
+    items: Vec<(usize, usize)>,
+    seen: usize,
+}
+
+impl Reservoir {
+    fn new(k: usize) -> Self {
+        // Don't reserve capacity from the raw user-supplied k; a huge -n on
+        // a small file should fall back to sampling everything, not OOM.
+        Reservoir {
+            k,
+            items: Vec::new(),
+            seen: 0,
+        }
+    }
+
+    fn observe(&mut self, chunk: (usize, usize), rng: &mut impl rand::Rng) {
+        if self.seen < self.k {
+            self.items.push(chunk);
+        } else {
+            let r = rng.random_range(0..=self.seen);
+            if r < self.k {
+                self.items[r] = chunk;
             }
-        });
-    
-    chunk_count.load(Ordering::Relaxed)
+        }
+        self.seen += 1;
+    }
 }
 
-fn build_chunk_index(data: &[u8], delimiter: &[u8]) -> Vec<(usize, usize)> {
-    let mut chunks = Vec::new();
-    
+fn reservoir_sample_chunks(
+    data: &[u8],
+    delimiter: &[u8],
+    k: usize,
+    rng: &mut impl rand::Rng,
+) -> Vec<(usize, usize)> {
+    let mut reservoir = Reservoir::new(k);
+
+    if k == 0 {
+        return reservoir.items;
+    }
+
     if delimiter.is_empty() || data.is_empty() {
-        chunks.push((0, data.len()));
-        return chunks;
+        reservoir.observe((0, data.len()), rng);
+        return reservoir.items;
     }
 
     let delim_len = delimiter.len();
@@ -134,7 +275,7 @@ fn build_chunk_index(data: &[u8], delimiter: &[u8]) -> Vec<(usize, usize)> {
 
     while i <= data.len().saturating_sub(delim_len) {
         if &data[i..i + delim_len] == delimiter {
-            chunks.push((chunk_start, i));
+            reservoir.observe((chunk_start, i), rng);
             chunk_start = i + delim_len;
             i += delim_len;
         } else {
@@ -142,12 +283,63 @@ fn build_chunk_index(data: &[u8], delimiter: &[u8]) -> Vec<(usize, usize)> {
         }
     }
 
-    // Add the last chunk if there's remaining data
     if chunk_start < data.len() {
-        chunks.push((chunk_start, data.len()));
+        reservoir.observe((chunk_start, data.len()), rng);
     }
 
-    chunks
+    reservoir.items
+}
+
+fn hash_chunk(bytes: &[u8]) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Keeps the first occurrence of each distinct chunk. The hash only picks the
+// bucket; chunks in the same bucket are still compared byte-for-byte, so a
+// hash collision can't silently drop a distinct chunk.
+fn dedup_chunks(mmap: &[u8], chunk_index: Vec<(usize, usize)>) -> (Vec<(usize, usize)>, usize) {
+    // Mirrors the write path's start < end guard against degenerate chunks.
+    let chunk_bytes = |(start, end): (usize, usize)| -> &[u8] {
+        if start < end {
+            &mmap[start..end]
+        } else {
+            &[]
+        }
+    };
+
+    let mut seen: HashMap<u64, Vec<(usize, usize)>> = HashMap::with_capacity(chunk_index.len());
+    let mut unique = Vec::with_capacity(chunk_index.len());
+    let mut duplicates = 0;
+
+    for chunk in chunk_index {
+        let bytes = chunk_bytes(chunk);
+        let bucket = seen.entry(hash_chunk(bytes)).or_default();
+
+        if bucket.iter().any(|&other| chunk_bytes(other) == bytes) {
+            duplicates += 1;
+        } else {
+            bucket.push(chunk);
+            unique.push(chunk);
+        }
+    }
+
+    (unique, duplicates)
+}
+
+// "-" means stdin, which isn't mmappable, so spill it to a temp file first.
+fn open_input(path: &PathBuf) -> io::Result<File> {
+    if path.as_os_str() == "-" {
+        let mut spill = tempfile::tempfile()?;
+        io::copy(&mut io::stdin(), &mut spill)?;
+        Ok(spill)
+    } else {
+        File::open(path)
+    }
 }
 
 fn main() -> io::Result<()> {
@@ -162,64 +354,93 @@ fn main() -> io::Result<()> {
     
     let delimiter = parse_delimiter(&args.delimiter);
     
-    let file = File::open(&args.input)?;
+    let file = open_input(&args.input)?;
     let mmap = unsafe { MmapOptions::new().map(&file)? };
-    
-    // Pass 1: Count chunks in parallel
-    let total_chunks = count_chunks_parallel(&mmap, &delimiter);
-    
-    if total_chunks == 0 {
-        return Ok(());
-    }
-    
-    // Pass 2: Build chunk index with a single scan
-    let chunk_index = build_chunk_index(&mmap, &delimiter);
-    
-    // Generate permutation based on seed
-    use hashed_permutation::HashedPermutation;
-    let permutation = if let Some(seed) = args.seed {
-        // Use the seed to create a deterministic permutation
-        let seed_u32 = (seed & 0xFFFFFFFF) as u32;
-        HashedPermutation {
-            seed: seed_u32,
-            length: NonZeroU32::new(chunk_index.len() as u32).unwrap(),
+
+    let seed = args.seed.unwrap_or_else(|| {
+        use rand::Rng;
+        rand::rng().random()
+    });
+
+    let mut chunk_index = if args.uniq {
+        // --uniq needs every chunk's content, so always build the full index.
+        let (unique, duplicate_count) = dedup_chunks(&mmap, build_chunk_index(&mmap, &delimiter));
+        if args.count_duplicates {
+            eprintln!("{} duplicate chunk(s) removed", duplicate_count);
         }
+        unique
+    } else if let Some(count) = args.count {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        reservoir_sample_chunks(&mmap, &delimiter, count, &mut rng)
     } else {
-        // Random permutation
-        use rand::Rng;
-        let mut rng = rand::rng();
-        let random_seed: u32 = rng.random();
-        HashedPermutation {
-            seed: random_seed,
-            length: NonZeroU32::new(chunk_index.len() as u32).unwrap(),
+        build_chunk_index(&mmap, &delimiter)
+    };
+
+    // --uniq with -n: sample COUNT out of the deduped set by shuffling and
+    // keeping a prefix, since Algorithm R can't stream over deduped content.
+    if args.uniq {
+        if let Some(count) = args.count {
+            if count < chunk_index.len() {
+                let sampling_permutation = FeistelPermutation::new(seed, chunk_index.len() as u64);
+                chunk_index = (0..count as u64)
+                    .map(|i| chunk_index[sampling_permutation.permute(i) as usize])
+                    .collect();
+            }
+        }
+    }
+
+    // Generate permutation based on seed
+    let permutation = FeistelPermutation::new(seed, chunk_index.len() as u64);
+
+    let had_trailing_delimiter = !delimiter.is_empty() && mmap.ends_with(&delimiter[..]);
+    let emit_trailing_delimiter = had_trailing_delimiter && !args.no_trailing_delimiter;
+
+    // Write to a sibling temp file and atomically rename into place for -o
+    let mut temp_output = match &args.output {
+        Some(path) => {
+            let dir = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or_else(|| std::path::Path::new("."));
+            Some(tempfile::NamedTempFile::new_in(dir)?)
         }
+        None => None,
     };
-    
-    // Output chunks in permuted order
+
     let stdout = io::stdout();
-    let mut handle = stdout.lock();
-    
-    for i in 0..chunk_index.len() {
-        // Get the permuted index for position i
-        let permuted_idx = match permutation.shuffle(i as u32) {
-            Ok(idx) => idx as usize,
-            Err(_) => continue,
+    let mut stdout_handle = stdout.lock();
+
+    {
+        let handle: &mut dyn Write = match &mut temp_output {
+            Some(file) => file,
+            None => &mut stdout_handle,
         };
-        
-        let (start, end) = chunk_index[permuted_idx];
-        if start < end {
-            let chunk_data = &mmap[start..end];
-            
-            // Write the chunk
-            handle.write_all(chunk_data)?;
-            
-            // Add delimiter after chunk if not the last one
-            if i < chunk_index.len() - 1 {
+
+        for i in 0..chunk_index.len() {
+            // Get the permuted index for position i
+            let permuted_idx = permutation.permute(i as u64) as usize;
+
+            let (start, end) = chunk_index[permuted_idx];
+            if start < end {
+                handle.write_all(&mmap[start..end])?;
+            }
+
+            // Add a delimiter after every chunk except the last, which only
+            // gets one if the input's trailing delimiter should be preserved.
+            if i < chunk_index.len() - 1 || emit_trailing_delimiter {
                 handle.write_all(&delimiter)?;
             }
         }
     }
-    
+
+    if let Some(path) = &args.output {
+        temp_output
+            .unwrap()
+            .persist(path)
+            .map_err(|persist_err| persist_err.error)?;
+    }
+
     Ok(())
 }
 
@@ -269,33 +490,175 @@ mod tests {
     }
 
     #[test]
-    fn test_count_chunks() {
+    fn test_build_chunk_index() {
         let data = b"a,b,c,d";
         let delimiter = b",";
-        assert_eq!(count_chunks_parallel(data, delimiter), 4);
+        let index = build_chunk_index(data, delimiter);
+
+        assert_eq!(index, vec![(0, 1), (2, 3), (4, 5), (6, 7)]);
     }
 
     #[test]
-    fn test_count_chunks_empty() {
+    fn test_build_chunk_index_empty() {
         let data = b"";
         let delimiter = b",";
-        assert_eq!(count_chunks_parallel(data, delimiter), 1);
+        assert_eq!(build_chunk_index(data, delimiter), vec![(0, 0)]);
     }
 
     #[test]
-    fn test_count_chunks_no_delimiter() {
+    fn test_build_chunk_index_no_delimiter() {
         let data = b"abcd";
         let delimiter = b",";
-        assert_eq!(count_chunks_parallel(data, delimiter), 1);
+        assert_eq!(build_chunk_index(data, delimiter), vec![(0, 4)]);
     }
 
     #[test]
-    fn test_build_chunk_index() {
-        let data = b"a,b,c,d";
-        let delimiter = b",";
-        let index = build_chunk_index(data, delimiter);
-        
-        assert_eq!(index, vec![(0, 1), (2, 3), (4, 5), (6, 7)]);
+    fn test_build_chunk_index_across_many_blocks() {
+        // Exercises the block-parallel path by using a delimiter that
+        // appears thousands of times, well past the 1MB minimum block size.
+        let chunks: Vec<String> = (0..200_000).map(|i| i.to_string()).collect();
+        let data = chunks.join(",");
+        let index = build_chunk_index(data.as_bytes(), b",");
+
+        assert_eq!(index.len(), chunks.len());
+        for (i, (start, end)) in index.iter().enumerate() {
+            assert_eq!(&data.as_bytes()[*start..*end], chunks[i].as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_build_chunk_index_self_overlapping_delimiter_across_blocks() {
+        // The leading `b` puts every serial "aa" match at an odd offset, while
+        // the parallel pass splits at even offsets (1MB or 1.5MB blocks), so a
+        // match straddles every seam whenever the data is split at all.
+        let mut data = vec![b'a'; 3_000_001];
+        data[0] = b'b';
+        let index = build_chunk_index(&data, b"aa");
+
+        assert_eq!(index.len(), 1_500_000);
+        assert_eq!(index[0], (0, 1));
+        let mut expected_start = 3;
+        for &(start, end) in &index[1..] {
+            assert_eq!(start, expected_start);
+            assert_eq!(end, expected_start);
+            expected_start += 2;
+        }
+    }
+
+    #[test]
+    fn test_dedup_chunks_keeps_first_occurrence() {
+        let data = b"a,b,a,c,b";
+        let index = build_chunk_index(data, b",");
+        let (unique, duplicates) = dedup_chunks(data, index);
+
+        assert_eq!(duplicates, 2);
+        assert_eq!(
+            unique.iter().map(|&(s, e)| &data[s..e]).collect::<Vec<_>>(),
+            vec![&b"a"[..], &b"b"[..], &b"c"[..]]
+        );
+    }
+
+    #[test]
+    fn test_dedup_chunks_no_duplicates() {
+        let data = b"a,b,c";
+        let index = build_chunk_index(data, b",");
+        let (unique, duplicates) = dedup_chunks(data, index.clone());
+
+        assert_eq!(duplicates, 0);
+        assert_eq!(unique, index);
+    }
+
+    #[test]
+    fn test_dedup_chunks_compares_bytes_not_just_hash() {
+        // dedup_chunks treats the hash only as a bucket key and compares bytes
+        // before calling two chunks equal. "ab" and "ba" hold the same bytes
+        // in a different order; nothing here forces them into one bucket, so
+        // this checks that they stay distinct while the repeated "ab" goes.
+        let data = b"ab,ba,ab";
+        let index = build_chunk_index(data, b",");
+        let (unique, duplicates) = dedup_chunks(data, index);
+
+        assert_eq!(duplicates, 1);
+        assert_eq!(
+            unique.iter().map(|&(s, e)| &data[s..e]).collect::<Vec<_>>(),
+            vec![&b"ab"[..], &b"ba"[..]]
+        );
+    }
+
+    #[test]
+    fn test_feistel_permutation_is_bijective() {
+        let permutation = FeistelPermutation::new(42, 17);
+        let mut seen = std::collections::HashSet::new();
+        for i in 0..17 {
+            let p = permutation.permute(i);
+            assert!(p < 17);
+            assert!(seen.insert(p), "duplicate output for domain 17: {}", p);
+        }
+    }
+
+    #[test]
+    fn test_feistel_permutation_deterministic_for_seed() {
+        let a = FeistelPermutation::new(7, 1000);
+        let b = FeistelPermutation::new(7, 1000);
+        for i in 0..1000 {
+            assert_eq!(a.permute(i), b.permute(i));
+        }
+    }
+
+    #[test]
+    fn test_feistel_permutation_single_element_domain() {
+        let permutation = FeistelPermutation::new(1, 1);
+        assert_eq!(permutation.permute(0), 0);
+    }
+
+    #[test]
+    fn test_reservoir_sample_chunks_size() {
+        use rand::SeedableRng;
+        let data = b"a,b,c,d,e,f,g,h";
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let sample = reservoir_sample_chunks(data, b",", 3, &mut rng);
+        assert_eq!(sample.len(), 3);
+    }
+
+    #[test]
+    fn test_reservoir_sample_chunks_count_exceeds_total() {
+        use rand::SeedableRng;
+        let data = b"a,b,c";
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let sample = reservoir_sample_chunks(data, b",", 10, &mut rng);
+        assert_eq!(sample.len(), 3);
+    }
+
+    #[test]
+    fn test_reservoir_sample_chunks_count_vastly_exceeds_total() {
+        // A count this large would abort on an eager `Vec::with_capacity(k)`
+        // long before a single chunk is scanned; it should just fall back to
+        // sampling the 3 chunks that actually exist.
+        use rand::SeedableRng;
+        let data = b"a,b,c";
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let sample = reservoir_sample_chunks(data, b",", 99_999_999_999_999, &mut rng);
+        assert_eq!(sample.len(), 3);
+    }
+
+    #[test]
+    fn test_reservoir_sample_chunks_zero_count() {
+        use rand::SeedableRng;
+        let data = b"a,b,c";
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let sample = reservoir_sample_chunks(data, b",", 0, &mut rng);
+        assert!(sample.is_empty());
+    }
+
+    #[test]
+    fn test_reservoir_sample_chunks_deterministic_for_seed() {
+        use rand::SeedableRng;
+        let data = b"a,b,c,d,e,f,g,h,i,j";
+        let mut rng_a = rand::rngs::StdRng::seed_from_u64(99);
+        let mut rng_b = rand::rngs::StdRng::seed_from_u64(99);
+        let sample_a = reservoir_sample_chunks(data, b",", 4, &mut rng_a);
+        let sample_b = reservoir_sample_chunks(data, b",", 4, &mut rng_b);
+        assert_eq!(sample_a, sample_b);
     }
 
     #[test]
@@ -1,6 +1,6 @@
 use std::fs::File;
 use std::io::Write;
-use std::process::Command;
+use std::process::{Command, Stdio};
 use tempfile::TempDir;
 
 #[test]
@@ -208,6 +208,191 @@ fn test_special_characters_in_content() {
     assert!(result.contains("end"));
 }
 
+#[test]
+fn test_count_emits_subset() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("subset.txt");
+
+    let mut file = File::create(&input_file).unwrap();
+    write!(file, "a,b,c,d,e,f,g,h,i,j").unwrap();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--", &input_file.to_string_lossy(), "-d", ",", "-n", "3"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let result = String::from_utf8_lossy(&output.stdout);
+
+    let parts: Vec<&str> = result.split(',').collect();
+    assert_eq!(parts.len(), 3);
+}
+
+#[test]
+fn test_count_exceeding_total_emits_everything() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("subset_all.txt");
+
+    let mut file = File::create(&input_file).unwrap();
+    write!(file, "a,b,c").unwrap();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--", &input_file.to_string_lossy(), "-d", ",", "-n", "100"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let result = String::from_utf8_lossy(&output.stdout);
+
+    assert!(result.contains('a'));
+    assert!(result.contains('b'));
+    assert!(result.contains('c'));
+}
+
+#[test]
+fn test_uniq_removes_duplicate_chunks() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("dupes.txt");
+
+    let mut file = File::create(&input_file).unwrap();
+    write!(file, "a,b,a,c,b,a").unwrap();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--", &input_file.to_string_lossy(), "-d", ",", "--uniq"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let result = String::from_utf8_lossy(&output.stdout);
+    let parts: Vec<&str> = result.split(',').collect();
+
+    assert_eq!(parts.len(), 3);
+}
+
+#[test]
+fn test_uniq_count_duplicates_reports_to_stderr() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("dupes_count.txt");
+
+    let mut file = File::create(&input_file).unwrap();
+    write!(file, "a,b,a,c,b,a").unwrap();
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--",
+            &input_file.to_string_lossy(),
+            "-d",
+            ",",
+            "--uniq",
+            "--count-duplicates",
+        ])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("3 duplicate"));
+}
+
+#[test]
+fn test_output_file_flag() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("input.txt");
+    let output_file = temp_dir.path().join("output.txt");
+
+    let mut file = File::create(&input_file).unwrap();
+    write!(file, "apple,banana,cherry").unwrap();
+
+    let status = Command::new("cargo")
+        .args(&[
+            "run",
+            "--",
+            &input_file.to_string_lossy(),
+            "-d",
+            ",",
+            "-o",
+            &output_file.to_string_lossy(),
+        ])
+        .status()
+        .expect("Failed to execute command");
+
+    assert!(status.success());
+    assert!(output_file.exists());
+
+    let result = std::fs::read_to_string(&output_file).unwrap();
+    assert!(result.contains("apple"));
+    assert!(result.contains("banana"));
+    assert!(result.contains("cherry"));
+}
+
+#[test]
+fn test_trailing_delimiter_preserved_by_default() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("trailing.txt");
+
+    let mut file = File::create(&input_file).unwrap();
+    write!(file, "a,b,c,").unwrap();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--", &input_file.to_string_lossy(), "-d", ","])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    assert!(output.stdout.ends_with(b","));
+}
+
+#[test]
+fn test_no_trailing_delimiter_flag_suppresses_it() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_file = temp_dir.path().join("trailing.txt");
+
+    let mut file = File::create(&input_file).unwrap();
+    write!(file, "a,b,c,").unwrap();
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--",
+            &input_file.to_string_lossy(),
+            "-d",
+            ",",
+            "--no-trailing-delimiter",
+        ])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    assert!(!output.stdout.ends_with(b","));
+}
+
+#[test]
+fn test_stdin_input() {
+    let mut child = Command::new("cargo")
+        .args(&["run", "--", "-", "-d", ","])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn command");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"apple,banana,cherry")
+        .unwrap();
+
+    let output = child.wait_with_output().expect("Failed to read output");
+
+    assert!(output.status.success());
+    let result = String::from_utf8_lossy(&output.stdout);
+
+    assert!(result.contains("apple"));
+    assert!(result.contains("banana"));
+    assert!(result.contains("cherry"));
+}
+
 #[test]
 fn test_seeded_shuffle_reproducible() {
     let temp_dir = TempDir::new().unwrap();